@@ -0,0 +1,231 @@
+// Copyright 2019-2020 PureStake Inc.
+// This file is part of Moonbeam.
+
+// Moonbeam is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Moonbeam is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A concrete `CanAuthor` implementation that restricts authorship to a rotating subset of a
+//! registered set of authors, instead of the fully-permissive `()` implementation. At each
+//! height a seed is derived from the parent hash and a stored randomness value, and used to
+//! pseudo-randomly select `eligible_ratio * num_authors` (at least one) of the registered
+//! authors as eligible to author this particular block.
+
+use crate::CanAuthor;
+use frame_support::{decl_module, decl_storage, Parameter};
+use parity_scale_codec::Encode;
+use sp_runtime::{
+	traits::{Hash, Member},
+	Percent,
+};
+use sp_std::prelude::*;
+
+pub trait Config: frame_system::Config {
+	/// The author identifier type used by the author-inherent pallet this filter backs.
+	type AuthorId: Member + Parameter;
+}
+
+decl_storage! {
+	trait Store for Module<T: Config> as AuthorEligibilityFilter {
+		/// The complete set of authors that are registered to author at all.
+		EligibleAuthors: Vec<T::AuthorId>;
+		/// The fraction of `EligibleAuthors` that may author any single block.
+		EligibleRatio: Percent = Percent::from_percent(100);
+		/// Randomness mixed into the per-block seed. Expected to be refreshed by some other
+		/// source (eg a relay-chain inherent); this pallet only reads it.
+		Randomness: T::Hash;
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Config> for enum Call where origin: T::Origin {}
+}
+
+impl<T: Config> Module<T> {
+	/// Pseudo-randomly selects the subset of `EligibleAuthors` that may author this block.
+	///
+	/// Starting from a seed mixing the parent hash with the stored `Randomness`, repeatedly
+	/// re-hashes the seed and takes `seed % len` as the next selected index, skipping indices
+	/// already chosen, until enough authors have been selected.
+	fn eligible_authors() -> Vec<T::AuthorId> {
+		let candidates = <EligibleAuthors<T>>::get();
+		if candidates.is_empty() {
+			return Vec::new();
+		}
+
+		let num_eligible = (<EligibleRatio<T>>::get() * candidates.len()).max(1);
+
+		let mut seed = T::Hashing::hash_of(&(
+			frame_system::Pallet::<T>::parent_hash(),
+			<Randomness<T>>::get(),
+		));
+
+		let mut selected_indices = Vec::with_capacity(num_eligible);
+		while selected_indices.len() < num_eligible && selected_indices.len() < candidates.len() {
+			seed = T::Hashing::hash(seed.as_ref());
+			let index = Self::index_from_seed(&seed, candidates.len());
+			if !selected_indices.contains(&index) {
+				selected_indices.push(index);
+			}
+		}
+
+		selected_indices
+			.into_iter()
+			.map(|index| candidates[index].clone())
+			.collect()
+	}
+
+	/// Reduces a hash to an index in `0..len` by reading up to its first 8 bytes as a
+	/// little-endian integer and taking it modulo `len`. Shorter hash encodings are zero-padded
+	/// rather than assumed away, since `eligibility::Config` doesn't bound `T::Hash`'s length.
+	fn index_from_seed(seed: &T::Hash, len: usize) -> usize {
+		let bytes = seed.as_ref();
+		let take = bytes.len().min(8);
+		let mut buf = [0u8; 8];
+		buf[..take].copy_from_slice(&bytes[..take]);
+		(u64::from_le_bytes(buf) % len as u64) as usize
+	}
+}
+
+impl<T: Config> CanAuthor<T::AuthorId> for Module<T> {
+	fn can_author(author: &T::AuthorId) -> bool {
+		Self::eligible_authors().contains(author)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use frame_support::parameter_types;
+	use sp_core::H256;
+	use sp_io::TestExternalities;
+	use sp_runtime::{
+		testing::Header,
+		traits::{BlakeTwo256, IdentityLookup},
+	};
+
+	pub fn new_test_ext() -> TestExternalities {
+		let t = frame_system::GenesisConfig::default()
+			.build_storage::<Test>()
+			.unwrap();
+		TestExternalities::new(t)
+	}
+
+	type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+	type Block = frame_system::mocking::MockBlock<Test>;
+
+	// Configure a mock runtime to test the pallet.
+	frame_support::construct_runtime!(
+		pub enum Test where
+			Block = Block,
+			NodeBlock = Block,
+			UncheckedExtrinsic = UncheckedExtrinsic,
+		{
+			System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+			Eligibility: crate::eligibility::{Pallet, Call, Storage},
+		}
+	);
+
+	parameter_types! {
+		pub const BlockHashCount: u64 = 250;
+	}
+	impl frame_system::Config for Test {
+		type BaseCallFilter = ();
+		type BlockWeights = ();
+		type BlockLength = ();
+		type DbWeight = ();
+		type Origin = Origin;
+		type Index = u64;
+		type BlockNumber = u64;
+		type Call = Call;
+		type Hash = H256;
+		type Hashing = BlakeTwo256;
+		type AccountId = u64;
+		type Lookup = IdentityLookup<Self::AccountId>;
+		type Header = Header;
+		type Event = ();
+		type BlockHashCount = BlockHashCount;
+		type Version = ();
+		type PalletInfo = PalletInfo;
+		type AccountData = ();
+		type OnNewAccount = ();
+		type OnKilledAccount = ();
+		type SystemWeightInfo = ();
+		type SS58Prefix = ();
+		type OnSetCode = ();
+	}
+	impl Config for Test {
+		type AuthorId = u64;
+	}
+
+	fn set_authors(authors: Vec<u64>) {
+		<EligibleAuthors<Test>>::put(authors);
+	}
+
+	#[test]
+	fn zero_ratio_still_selects_one_author() {
+		new_test_ext().execute_with(|| {
+			set_authors(vec![1, 2, 3, 4]);
+			<EligibleRatio<Test>>::put(Percent::from_percent(0));
+
+			assert_eq!(Module::<Test>::eligible_authors().len(), 1);
+		});
+	}
+
+	#[test]
+	fn full_ratio_selects_everyone() {
+		new_test_ext().execute_with(|| {
+			set_authors(vec![1, 2, 3, 4]);
+			<EligibleRatio<Test>>::put(Percent::from_percent(100));
+
+			let mut eligible = Module::<Test>::eligible_authors();
+			eligible.sort();
+			assert_eq!(eligible, vec![1, 2, 3, 4]);
+		});
+	}
+
+	#[test]
+	fn empty_author_set_is_never_eligible() {
+		new_test_ext().execute_with(|| {
+			<EligibleRatio<Test>>::put(Percent::from_percent(100));
+
+			for author in 1..=5u64 {
+				assert!(!Module::<Test>::can_author(&author));
+			}
+		});
+	}
+
+	#[test]
+	fn selection_changes_with_randomness() {
+		new_test_ext().execute_with(|| {
+			set_authors(vec![1, 2, 3, 4, 5, 6, 7, 8]);
+			<EligibleRatio<Test>>::put(Percent::from_percent(25));
+
+			let baseline = Module::<Test>::eligible_authors();
+
+			let mut saw_different_selection = false;
+			for byte in 1u8..=8 {
+				<Randomness<Test>>::put(H256::repeat_byte(byte));
+				if Module::<Test>::eligible_authors() != baseline {
+					saw_different_selection = true;
+					break;
+				}
+			}
+
+			assert!(
+				saw_different_selection,
+				"varying the randomness seed should eventually change the eligible set"
+			);
+		});
+	}
+}
@@ -15,11 +15,14 @@
 // along with Moonbeam.  If not, see <http://www.gnu.org/licenses/>.
 
 //! Pallet that allows block authors to include their identity in a block via an inherent.
-//! Currently the author does not _prove_ their identity, just states it. So it should not be used,
-//! for things like equivocation slashing that require authenticated authorship information.
+//! The author proves their identity by signing the parent block hash with the key associated
+//! to the claimed `AuthorId`, so the reported authorship is authenticated and can be relied
+//! upon downstream, e.g. for equivocation slashing.
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
+pub mod eligibility;
+
 use frame_support::{
 	decl_error, decl_module, decl_storage, ensure,
 	traits::FindAuthor,
@@ -51,8 +54,8 @@ impl<T> EventHandler<T> for () {
 pub trait CanAuthor<AuthorId> {
 	fn can_author(author: &AuthorId) -> bool;
 }
-/// Default implementation where anyone can author, see and `author-*-filter` pallets for
-/// additional implementations.
+/// Default implementation where anyone can author. See [`eligibility::Module`] for a
+/// rotating, eligibility-ratio-based implementation.
 /// TODO Promote this is "implementing relay chain consensus in the nimbus framework."
 impl<T> CanAuthor<T> for () {
 	fn can_author(_: &T) -> bool {
@@ -62,8 +65,9 @@ impl<T> CanAuthor<T> for () {
 
 pub trait Config: frame_system::Config {
 	// This is copied from Aura. I wonder if I really need all those trait bounds. For now I'll leave them.
-	/// The identifier type for an authority.
-	type AuthorId: Member + Parameter;
+	/// The identifier type for an authority. Must be a `RuntimeAppPublic` so that claimed
+	/// authorship can be authenticated with a signature over the parent block hash.
+	type AuthorId: Member + Parameter + RuntimeAppPublic;
 
 	//TODO do we have any use for this converter?
 	// It has to happen eventually to pay rewards to accountids and let account ids stake.
@@ -88,12 +92,8 @@ pub trait Config: frame_system::Config {
 	type FullCanAuthor: CanAuthor<Self::AuthorId>;
 }
 
-// If the AccountId type supports it, then this pallet can be BoundToRuntimeAppPublic
-impl<T> sp_runtime::BoundToRuntimeAppPublic for Module<T>
-where
-	T: Config,
-	T::AuthorId: RuntimeAppPublic,
-{
+// The AuthorId is always a RuntimeAppPublic, so this pallet can be BoundToRuntimeAppPublic
+impl<T: Config> sp_runtime::BoundToRuntimeAppPublic for Module<T> {
 	type Public = T::AuthorId;
 }
 
@@ -103,6 +103,10 @@ decl_error! {
 		AuthorAlreadySet,
 		/// The author in the inherent is not an eligible author.
 		CannotBeAuthor,
+		/// The signature on the author inherent does not match the claimed author.
+		InvalidAuthorSignature,
+		/// The claimed author does not match the PreRuntime digest deposited by the block author.
+		AuthorDigestMismatch,
 	}
 }
 
@@ -110,6 +114,10 @@ decl_storage! {
 	trait Store for Module<T: Config> as Author {
 		/// Author of current block.
 		Author: Option<T::AuthorId>;
+		/// Whether the author inherent was included in this block. Checked in `on_finalize`
+		/// so a block that omits the mandatory inherent is rejected rather than silently
+		/// accepted, which `is_inherent_required` alone cannot enforce.
+		InherentIncluded: bool;
 	}
 }
 
@@ -119,15 +127,27 @@ decl_module! {
 
 		fn on_initialize() -> Weight {
 			<Author<T>>::kill();
+			<InherentIncluded<T>>::kill();
 			0
 		}
 
+		fn on_finalize() {
+			assert!(
+				<InherentIncluded<T>>::get(),
+				"Block invalid, missing author inherent"
+			);
+		}
+
 		/// Inherent to set the author of a block
 		#[weight = (
 			0,
 			DispatchClass::Mandatory
 		)]
-		fn set_author(origin, author: T::AuthorId) {
+		fn set_author(
+			origin,
+			author: T::AuthorId,
+			signature: <T::AuthorId as RuntimeAppPublic>::Signature,
+		) {
 
 			ensure_none(origin)?;
 			debug!(target: "author-inherent", "Executing Author inherent");
@@ -136,16 +156,25 @@ decl_module! {
 			ensure!(T::FullCanAuthor::can_author(&author), Error::<T>::CannotBeAuthor);
 			debug!(target: "author-inherent", "I can be author!");
 
+			let parent_hash = frame_system::Pallet::<T>::parent_hash();
+			ensure!(
+				author.verify(&parent_hash.encode(), &signature),
+				Error::<T>::InvalidAuthorSignature
+			);
+			debug!(target: "author-inherent", "Author's signature over the parent hash checks out");
+
+			// The block author must have already deposited a PreRuntime digest naming the
+			// author before executing any extrinsics. Check that it agrees with this inherent,
+			// rather than depositing our own digest after the fact.
+			ensure!(
+				pre_runtime_author::<T>() == Some(author.clone()),
+				Error::<T>::AuthorDigestMismatch
+			);
+			debug!(target: "author-inherent", "Author matches the PreRuntime digest");
+
 			// Update storage
 			Author::<T>::put(&author);
-
-			// Add a digest item so Apps can detect the block author
-			// For now we use the Consensus digest item.
-			// Maybe this will change later.
-			frame_system::Pallet::<T>::deposit_log(DigestItem::<T::Hash>::Consensus(
-				ENGINE_ID,
-				author.encode(),
-			));
+			<InherentIncluded<T>>::put(true);
 
 			// Notify any other pallets that are listening (eg rewards) about the author
 			T::EventHandler::note_author(author);
@@ -153,13 +182,32 @@ decl_module! {
 	}
 }
 
+/// Extracts the author claimed in this block's `ENGINE_ID` PreRuntime digest, if any.
+fn pre_runtime_author<T: Config>() -> Option<T::AuthorId> {
+	frame_system::Pallet::<T>::digest()
+		.logs()
+		.iter()
+		.filter_map(DigestItem::as_pre_runtime)
+		.find(|(id, _)| id == &ENGINE_ID)
+		.and_then(|(_, mut data)| T::AuthorId::decode(&mut data).ok())
+}
+
 impl<T: Config> FindAuthor<T::AuthorId> for Module<T> {
-	fn find_author<'a, I>(_digests: I) -> Option<T::AuthorId>
+	fn find_author<'a, I>(digests: I) -> Option<T::AuthorId>
 	where
 		I: 'a + IntoIterator<Item = (ConsensusEngineId, &'a [u8])>,
 	{
-		// We don't use the digests at all.
-		// This will only return the correct author _after_ the authorship inherent is processed.
+		// The author is known as soon as the block author's PreRuntime digest is present,
+		// i.e. before the authorship inherent has executed.
+		for (id, mut data) in digests {
+			if id == ENGINE_ID {
+				if let Ok(author) = T::AuthorId::decode(&mut data) {
+					return Some(author);
+				}
+			}
+		}
+
+		// Fall back to storage, e.g. for callers that only have the post-execution state.
 		<Author<T>>::get()
 	}
 }
@@ -195,17 +243,34 @@ impl InherentError {
 	}
 }
 
-/// The type of data that the inherent will contain.
-pub type InherentType<T> = <T as Config>::AuthorId;
+/// The type of data that the inherent will contain: the claimed author together with their
+/// signature over the parent block hash, proving control of the author's key.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct InherentType<AuthorId: RuntimeAppPublic> {
+	/// The author claiming to have produced this block.
+	pub author: AuthorId,
+	/// Signature of `author` over the parent block hash.
+	pub signature: AuthorId::Signature,
+}
 
 /// A thing that an outer node could use to inject the inherent data.
 /// This should be used in simple uses of the author inherent (eg permissionless authoring)
 /// When using the full nimbus system, we are manually inserting the  inherent.
 #[cfg(feature = "std")]
-pub struct InherentDataProvider<AuthorId>(pub AuthorId);
+pub struct InherentDataProvider<AuthorId, Hash> {
+	/// The author to claim in the inherent, and whose key is used to sign `parent_hash`.
+	pub author: AuthorId,
+	/// The hash of the parent block, which is the message being signed.
+	pub parent_hash: Hash,
+}
 
 #[cfg(feature = "std")]
-impl<AuthorId: Encode> ProvideInherentData for InherentDataProvider<AuthorId> {
+impl<AuthorId, Hash> ProvideInherentData for InherentDataProvider<AuthorId, Hash>
+where
+	AuthorId: RuntimeAppPublic + Encode + Clone,
+	Hash: Encode,
+{
 	fn inherent_identifier(&self) -> &'static InherentIdentifier {
 		&INHERENT_IDENTIFIER
 	}
@@ -214,7 +279,18 @@ impl<AuthorId: Encode> ProvideInherentData for InherentDataProvider<AuthorId> {
 		&self,
 		inherent_data: &mut InherentData,
 	) -> Result<(), sp_inherents::Error> {
-		inherent_data.put_data(INHERENT_IDENTIFIER, &self.0)
+		let signature = self.author.sign(&self.parent_hash.encode()).expect(
+			"The node's keystore contains the key needed to author this block, and is able \
+			to sign the parent hash with it; qed",
+		);
+
+		inherent_data.put_data(
+			INHERENT_IDENTIFIER,
+			&InherentType {
+				author: self.author.clone(),
+				signature,
+			},
+		)
 	}
 
 	fn error_to_string(&self, error: &[u8]) -> Option<String> {
@@ -236,40 +312,48 @@ impl<T: Config> ProvideInherent for Module<T> {
 	}
 
 	fn create_inherent(data: &InherentData) -> Option<Self::Call> {
-		// Grab the Vec<u8> labelled with "author__" from the map of all inherent data
-		let author_raw = data
-			.get_data::<InherentType<T>>(&INHERENT_IDENTIFIER);
+		// Grab the author and signature from the map of all inherent data
+		let inherent_data = data
+			.get_data::<InherentType<T::AuthorId>>(&INHERENT_IDENTIFIER);
 
 		debug!("In create_inherent (runtime side). data is");
-		debug!("{:?}", author_raw);
+		debug!("{:?}", inherent_data);
 
-		let author = author_raw
+		let inherent_data = inherent_data
 			.expect("Gets and decodes authorship inherent data")?;
 
-		//TODO we need to make the author _prove_ their identity, not just claim it.
-		// we should have them sign something here. Best idea so far: parent block hash.
-
-		// Decode the Vec<u8> into an account Id
-		// let author =
-		// 	T::AuthorId::decode(&mut &author_raw[..]).expect("Decodes author raw inherent data");
-
-		Some(Call::set_author(author))
+		Some(Call::set_author(inherent_data.author, inherent_data.signature))
 	}
 
 	fn check_inherent(call: &Self::Call, _data: &InherentData) -> Result<(), Self::Error> {
 		// We only check this pallet's inherent.
-		if let Self::Call::set_author(claimed_author) = call {
+		if let Self::Call::set_author(claimed_author, signature) = call {
 			ensure!(
 				T::PreliminaryCanAuthor::can_author(&claimed_author),
 				InherentError::Other(sp_runtime::RuntimeString::Borrowed("Cannot Be Author"))
 			);
+
+			let parent_hash = frame_system::Pallet::<T>::parent_hash();
+			ensure!(
+				claimed_author.verify(&parent_hash.encode(), signature),
+				InherentError::Other(sp_runtime::RuntimeString::Borrowed(
+					"Invalid Author Signature"
+				))
+			);
+
+			ensure!(
+				pre_runtime_author::<T>().as_ref() == Some(claimed_author),
+				InherentError::Other(sp_runtime::RuntimeString::Borrowed(
+					"Author Does Not Match PreRuntime Digest"
+				))
+			);
 		}
 
 		Ok(())
 	}
 
 	fn is_inherent(call: &Self::Call) -> bool {
-		matches!(call, Call::set_author(_))
+		matches!(call, Call::set_author(_, _))
 	}
 }
 
@@ -285,7 +369,7 @@ mod tests {
 	use sp_core::H256;
 	use sp_io::TestExternalities;
 	use sp_runtime::{
-		testing::Header,
+		testing::{Header, UintAuthorityId},
 		traits::{BlakeTwo256, IdentityLookup},
 	};
 
@@ -340,14 +424,21 @@ mod tests {
 		type OnSetCode = ();
 	}
 	impl Config for Test {
-		type AuthorId = u64;
+		type AuthorId = UintAuthorityId;
 		type EventHandler = ();
 		type PreliminaryCanAuthor = ();
 		type FullCanAuthor = ();
 	}
 
+	/// Deposits the PreRuntime digest a block author would have added before executing
+	/// extrinsics, so the inherent's digest check has something to compare against.
+	fn deposit_author_digest(author: &UintAuthorityId) {
+		System::deposit_log(DigestItem::PreRuntime(ENGINE_ID, author.encode()));
+	}
+
 	pub fn roll_to(n: u64) {
 		while System::block_number() < n {
+			AuthorInherent::on_finalize(System::block_number());
 			System::on_finalize(System::block_number());
 			System::set_block_number(System::block_number() + 1);
 			System::on_initialize(System::block_number());
@@ -358,9 +449,18 @@ mod tests {
 	#[test]
 	fn set_author_works() {
 		new_test_ext().execute_with(|| {
-			assert_ok!(AuthorInherent::set_author(Origin::none(), 1));
+			let author = UintAuthorityId(1);
+			deposit_author_digest(&author);
+			let signature = author.sign(&System::parent_hash().encode()).unwrap();
+			assert_ok!(AuthorInherent::set_author(
+				Origin::none(),
+				author.clone(),
+				signature
+			));
 			roll_to(1);
-			assert_ok!(AuthorInherent::set_author(Origin::none(), 1));
+			deposit_author_digest(&author);
+			let signature = author.sign(&System::parent_hash().encode()).unwrap();
+			assert_ok!(AuthorInherent::set_author(Origin::none(), author, signature));
 			roll_to(2);
 		});
 	}
@@ -368,8 +468,10 @@ mod tests {
 	#[test]
 	fn must_be_inherent() {
 		new_test_ext().execute_with(|| {
+			let author = UintAuthorityId(1);
+			let signature = author.sign(&System::parent_hash().encode()).unwrap();
 			assert_noop!(
-				AuthorInherent::set_author(Origin::signed(1), 1),
+				AuthorInherent::set_author(Origin::signed(1), author, signature),
 				sp_runtime::DispatchError::BadOrigin
 			);
 		});
@@ -378,11 +480,83 @@ mod tests {
 	#[test]
 	fn double_author_fails() {
 		new_test_ext().execute_with(|| {
-			assert_ok!(AuthorInherent::set_author(Origin::none(), 1));
+			let author = UintAuthorityId(1);
+			deposit_author_digest(&author);
+			let signature = author.sign(&System::parent_hash().encode()).unwrap();
+			assert_ok!(AuthorInherent::set_author(
+				Origin::none(),
+				author.clone(),
+				signature.clone()
+			));
 			assert_noop!(
-				AuthorInherent::set_author(Origin::none(), 1),
+				AuthorInherent::set_author(Origin::none(), author, signature),
 				Error::<Test>::AuthorAlreadySet
 			);
 		});
 	}
+
+	#[test]
+	fn wrong_signature_fails() {
+		new_test_ext().execute_with(|| {
+			let author = UintAuthorityId(1);
+			let bad_signature = UintAuthorityId(2)
+				.sign(&System::parent_hash().encode())
+				.unwrap();
+			assert_noop!(
+				AuthorInherent::set_author(Origin::none(), author, bad_signature),
+				Error::<Test>::InvalidAuthorSignature
+			);
+		});
+	}
+
+	#[test]
+	fn mismatched_digest_fails() {
+		new_test_ext().execute_with(|| {
+			let author = UintAuthorityId(1);
+			let other = UintAuthorityId(2);
+			deposit_author_digest(&other);
+			let signature = author.sign(&System::parent_hash().encode()).unwrap();
+			assert_noop!(
+				AuthorInherent::set_author(Origin::none(), author, signature),
+				Error::<Test>::AuthorDigestMismatch
+			);
+		});
+	}
+
+	#[test]
+	fn find_author_reads_pre_runtime_digest() {
+		new_test_ext().execute_with(|| {
+			let author = UintAuthorityId(1);
+			deposit_author_digest(&author);
+			let digest = System::digest();
+			let digests = digest.logs().iter().filter_map(|log| log.as_pre_runtime());
+			assert_eq!(AuthorInherent::find_author(digests), Some(author));
+		});
+	}
+
+	#[test]
+	fn unrelated_pre_runtime_digest_ahead_of_ours_is_skipped() {
+		new_test_ext().execute_with(|| {
+			// Some other consensus engine (eg Aura) may have already deposited its own
+			// PreRuntime digest before ours; it must not shadow the author digest.
+			System::deposit_log(DigestItem::PreRuntime(*b"aura", vec![0xFF; 4]));
+			let author = UintAuthorityId(1);
+			deposit_author_digest(&author);
+
+			let digest = System::digest();
+			let digests = digest.logs().iter().filter_map(|log| log.as_pre_runtime());
+			assert_eq!(AuthorInherent::find_author(digests), Some(author.clone()));
+
+			let signature = author.sign(&System::parent_hash().encode()).unwrap();
+			assert_ok!(AuthorInherent::set_author(Origin::none(), author, signature));
+		});
+	}
+
+	#[test]
+	#[should_panic(expected = "Block invalid, missing author inherent")]
+	fn missing_inherent_panics_on_finalize() {
+		new_test_ext().execute_with(|| {
+			roll_to(1);
+		});
+	}
 }
\ No newline at end of file